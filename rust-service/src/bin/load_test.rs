@@ -1,8 +1,11 @@
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::Message;
 
 #[derive(Debug, Serialize)]
 struct Event {
@@ -11,6 +14,79 @@ struct Event {
     value: f64,
 }
 
+/// HDR-style latency histogram: logarithmic (power-of-two) buckets covering
+/// roughly 1µs to 60s, updated lock-free from every request so percentiles
+/// don't require keeping every sample around.
+struct LatencyHistogram {
+    buckets: Vec<AtomicU64>,
+    min_micros: AtomicU64,
+    max_micros: AtomicU64,
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl LatencyHistogram {
+    // log2(60 seconds in micros) ~= 25.8, so 27 buckets comfortably covers it.
+    const NUM_BUCKETS: usize = 27;
+
+    fn new() -> Self {
+        Self {
+            buckets: (0..Self::NUM_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            min_micros: AtomicU64::new(u64::MAX),
+            max_micros: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros().max(1) as u64;
+        let bucket = (64 - micros.leading_zeros() as usize).min(Self::NUM_BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.min_micros.fetch_min(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Upper bound (ms) of the smallest bucket whose cumulative count
+    /// reaches the `p`th percentile (`p` in `0.0..=1.0`).
+    fn percentile_ms(&self, p: f64) -> f64 {
+        let counts: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let target = ((p * total as f64).ceil() as u64).max(1);
+        let mut cumulative = 0u64;
+        for (bucket, &count) in counts.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= target {
+                let bucket_upper_micros = if bucket == 0 { 1 } else { 1u64 << bucket };
+                return bucket_upper_micros as f64 / 1000.0;
+            }
+        }
+        0.0
+    }
+
+    fn min_ms(&self) -> f64 {
+        let v = self.min_micros.load(Ordering::Relaxed);
+        if v == u64::MAX { 0.0 } else { v as f64 / 1000.0 }
+    }
+
+    fn max_ms(&self) -> f64 {
+        self.max_micros.load(Ordering::Relaxed) as f64 / 1000.0
+    }
+
+    fn mean_ms(&self) -> f64 {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return 0.0;
+        }
+        (self.sum_micros.load(Ordering::Relaxed) as f64 / count as f64) / 1000.0
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct Stats {
     #[serde(rename = "totalRequests")]
@@ -21,6 +97,12 @@ struct Stats {
     avg: f64,
 }
 
+#[derive(Debug, Deserialize)]
+struct BatchResponse {
+    accepted: usize,
+    rejected: usize,
+}
+
 #[derive(Clone)]
 struct LoadTestClient {
     client: reqwest::Client,
@@ -28,6 +110,8 @@ struct LoadTestClient {
     total_requests: Arc<AtomicU64>,
     successful_requests: Arc<AtomicU64>,
     failed_requests: Arc<AtomicU64>,
+    latency: Arc<LatencyHistogram>,
+    status_counts: Arc<Mutex<HashMap<u16, u64>>>,
 }
 
 impl LoadTestClient {
@@ -46,6 +130,8 @@ impl LoadTestClient {
             total_requests: Arc::new(AtomicU64::new(0)),
             successful_requests: Arc::new(AtomicU64::new(0)),
             failed_requests: Arc::new(AtomicU64::new(0)),
+            latency: Arc::new(LatencyHistogram::new()),
+            status_counts: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -58,13 +144,21 @@ impl LoadTestClient {
 
     async fn send_event(&self, event: Event) -> Result<(), reqwest::Error> {
         self.total_requests.fetch_add(1, Ordering::Relaxed);
-        
+        let start = Instant::now();
+
         let response = self.client
             .post(&format!("{}/event", self.base_url))
             .json(&event)
             .send()
             .await?;
 
+        self.latency.record(start.elapsed());
+        *self.status_counts
+            .lock()
+            .unwrap()
+            .entry(response.status().as_u16())
+            .or_insert(0) += 1;
+
         if response.status().is_success() {
             self.successful_requests.fetch_add(1, Ordering::Relaxed);
         } else {
@@ -74,6 +168,57 @@ impl LoadTestClient {
         Ok(())
     }
 
+    /// Send a batch of events in a single request to `/events`, amortizing
+    /// HTTP/serialization overhead across `events.len()` events.
+    async fn send_events_batch(&self, events: Vec<Event>) -> Result<BatchResponse, reqwest::Error> {
+        self.total_requests.fetch_add(events.len() as u64, Ordering::Relaxed);
+        let start = Instant::now();
+
+        let response = self.client
+            .post(&format!("{}/events", self.base_url))
+            .json(&events)
+            .send()
+            .await?;
+
+        self.latency.record(start.elapsed());
+        *self.status_counts
+            .lock()
+            .unwrap()
+            .entry(response.status().as_u16())
+            .or_insert(0) += 1;
+
+        let summary: BatchResponse = response.json().await?;
+        self.successful_requests
+            .fetch_add(summary.accepted as u64, Ordering::Relaxed);
+        self.failed_requests
+            .fetch_add(summary.rejected as u64, Ordering::Relaxed);
+
+        Ok(summary)
+    }
+
+    /// Stream events over a single long-lived WebSocket connection instead
+    /// of paying a full HTTP request per event.
+    async fn run_ws_load_test(
+        &self,
+        total_requests: usize,
+        user_offset: u64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let ws_url = format!("{}/ws", self.base_url.replacen("http", "ws", 1));
+        let (ws_stream, _) = tokio_tungstenite::connect_async(&ws_url).await?;
+        let (mut sink, _stream) = ws_stream.split();
+
+        for i in 0..total_requests {
+            let event = self.generate_event(user_offset + i as u64);
+            let payload = serde_json::to_string(&event)?;
+            sink.send(Message::Text(payload)).await?;
+            self.total_requests.fetch_add(1, Ordering::Relaxed);
+            self.successful_requests.fetch_add(1, Ordering::Relaxed);
+        }
+
+        sink.close().await?;
+        Ok(())
+    }
+
     async fn get_stats(&self) -> Result<Stats, reqwest::Error> {
         let response = self.client
             .get(&format!("{}/stats", self.base_url))
@@ -169,6 +314,157 @@ impl LoadTestClient {
         self.print_results(duration, &final_stats);
     }
 
+    /// Like [`Self::run_load_test`], but drives requests through `/events` in
+    /// batches of `batch_size` instead of one `/event` call per request, so
+    /// the 1,000,000-request challenge can be driven with far fewer HTTP
+    /// round-trips.
+    async fn run_batch_load_test(&self, total_requests: usize, batch_size: usize, max_concurrent_batches: usize) {
+        println!("🚀 Starting Rust load test (batched /events)");
+        println!("📊 Target: {} requests", total_requests);
+        println!("📦 Batch size: {}", batch_size);
+        println!("🔄 Max concurrent batches: {}", max_concurrent_batches);
+
+        // Test connectivity
+        print!("🔌 Testing connectivity... ");
+        match self.get_stats().await {
+            Ok(stats) => println!("✅ Connected! Initial stats: {:?}", stats),
+            Err(e) => {
+                println!("❌ Connection failed: {}", e);
+                return;
+            }
+        }
+
+        let start_time = Instant::now();
+        let num_batches = (total_requests + batch_size - 1) / batch_size;
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(max_concurrent_batches));
+
+        let mut batch_tasks = Vec::new();
+
+        for batch_idx in 0..num_batches {
+            let client = self.clone();
+            let semaphore = semaphore.clone();
+            let user_offset = (batch_idx * batch_size) as u64;
+            let current_batch_size = std::cmp::min(batch_size, total_requests - batch_idx * batch_size);
+
+            let task = tokio::spawn(async move {
+                let _permit = semaphore.acquire().await.unwrap();
+                let events: Vec<Event> = (0..current_batch_size)
+                    .map(|i| client.generate_event(user_offset + i as u64))
+                    .collect();
+                if let Err(e) = client.send_events_batch(events).await {
+                    eprintln!("Batch request failed: {}", e);
+                    client.failed_requests.fetch_add(current_batch_size as u64, Ordering::Relaxed);
+                }
+            });
+
+            batch_tasks.push(task);
+
+            // Progress reporting
+            if (batch_idx + 1) % 100 == 0 {
+                println!("📈 Queued {}/{} batches", batch_idx + 1, num_batches);
+            }
+        }
+
+        println!("⚡ Executing all requests...");
+
+        // Wait for all batches to complete
+        for task in batch_tasks {
+            let _ = task.await;
+        }
+
+        let duration = start_time.elapsed();
+
+        // Wait a moment for the server to process final requests
+        sleep(Duration::from_millis(100)).await;
+
+        // Get final statistics
+        println!("📋 Fetching final statistics...");
+        let final_stats = self.get_stats().await.unwrap_or_else(|e| {
+            eprintln!("Failed to get final stats: {}", e);
+            Stats {
+                total_requests: 0,
+                unique_users: 0,
+                sum: 0.0,
+                avg: 0.0,
+            }
+        });
+
+        self.print_results(duration, &final_stats);
+    }
+
+    /// Open-loop, rate-controlled load generation: dispatches requests on a
+    /// fixed schedule (independent of response time) instead of waiting for
+    /// one batch to finish before starting the next. Ramps the target rate
+    /// by `rate_step` every `step_duration` until `rate_max`, so the caller
+    /// can find the point where the server falls behind.
+    async fn run_rate_controlled(
+        &self,
+        rate: f64,
+        rate_step: f64,
+        rate_max: f64,
+        step_duration: Duration,
+        max_iter: u64,
+    ) {
+        println!("🚀 Starting open-loop rate-controlled load test");
+        println!(
+            "📈 Rate: {:.1} req/s -> {:.1} req/s (step {:.1}), {:?} per step",
+            rate, rate_max, rate_step, step_duration
+        );
+
+        let mut current_rate = rate;
+        let mut user_id = 0u64;
+        let mut iter = 0u64;
+
+        while current_rate <= rate_max && iter < max_iter {
+            let period = Duration::from_secs_f64(1.0 / current_rate);
+            let mut ticker = tokio::time::interval(period);
+            let step_start = Instant::now();
+
+            let step_sent = Arc::new(AtomicU64::new(0));
+            let step_successful = Arc::new(AtomicU64::new(0));
+            let step_failed = Arc::new(AtomicU64::new(0));
+            let mut tasks = Vec::new();
+
+            while step_start.elapsed() < step_duration {
+                ticker.tick().await;
+
+                let client = self.clone();
+                let event = self.generate_event(user_id);
+                user_id += 1;
+                let step_sent = step_sent.clone();
+                let step_successful = step_successful.clone();
+                let step_failed = step_failed.clone();
+
+                tasks.push(tokio::spawn(async move {
+                    step_sent.fetch_add(1, Ordering::Relaxed);
+                    match client.send_event(event).await {
+                        Ok(_) => {
+                            step_successful.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(_) => {
+                            step_failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }));
+            }
+
+            for task in tasks {
+                let _ = task.await;
+            }
+
+            println!(
+                "📊 Step @ {:.1} req/s: sent {} / ok {} / failed {}",
+                current_rate,
+                step_sent.load(Ordering::Relaxed),
+                step_successful.load(Ordering::Relaxed),
+                step_failed.load(Ordering::Relaxed),
+            );
+
+            current_rate += rate_step;
+            iter += 1;
+        }
+    }
+
     fn print_results(&self, duration: Duration, server_stats: &Stats) {
         let total = self.total_requests.load(Ordering::Relaxed);
         let successful = self.successful_requests.load(Ordering::Relaxed);
@@ -185,7 +481,18 @@ impl LoadTestClient {
         println!("   • Failed: {} ({:.1}%)", failed, (failed as f64 / total as f64) * 100.0);
         println!("🚄 Performance:");
         println!("   • Requests/second: {:.2}", total as f64 / duration_secs);
-        println!("   • Avg latency: {:.2}ms", (duration_secs * 1000.0) / total as f64);
+        println!("⏳ Latency (round-trip, per request):");
+        println!("   • Min:  {:.2}ms", self.latency.min_ms());
+        println!("   • Mean: {:.2}ms", self.latency.mean_ms());
+        println!("   • p50:  {:.2}ms", self.latency.percentile_ms(0.50));
+        println!("   • p90:  {:.2}ms", self.latency.percentile_ms(0.90));
+        println!("   • p95:  {:.2}ms", self.latency.percentile_ms(0.95));
+        println!("   • p99:  {:.2}ms", self.latency.percentile_ms(0.99));
+        println!("   • Max:  {:.2}ms", self.latency.max_ms());
+        println!("📟 Status codes:");
+        for (status, count) in self.status_counts.lock().unwrap().iter() {
+            println!("   • {}: {}", status, count);
+        }
         println!("\n📊 Server statistics:");
         println!("   • Total requests: {}", server_stats.total_requests);
         println!("   • Unique users: {}", server_stats.unique_users);
@@ -201,27 +508,85 @@ impl LoadTestClient {
     }
 }
 
+/// Which request strategy to drive the challenge with, selected by the 2nd
+/// CLI argument or the `LOADTEST_MODE` env var (argument wins). Defaults to
+/// `closed`, matching this binary's original one-request-per-`/event` shape.
+#[derive(Debug, PartialEq, Eq)]
+enum Mode {
+    /// Closed-loop: wait for each batch of requests before starting the next.
+    Closed,
+    /// Open-loop, rate-controlled ramp via `run_rate_controlled`.
+    Rate,
+    /// Batched via `/events` via `run_batch_load_test`.
+    Batch,
+    /// Streamed over a single `/ws` connection via `run_ws_load_test`.
+    Ws,
+}
+
+fn parse_mode() -> Mode {
+    let mode = std::env::args()
+        .nth(2)
+        .or_else(|| std::env::var("LOADTEST_MODE").ok())
+        .unwrap_or_else(|| "closed".to_string());
+
+    match mode.as_str() {
+        "rate" => Mode::Rate,
+        "batch" => Mode::Batch,
+        "ws" => Mode::Ws,
+        _ => Mode::Closed,
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let base_url = std::env::args()
         .nth(1)
         .unwrap_or_else(|| "http://127.0.0.1:8080".to_string());
-    
+
     let client = LoadTestClient::new(&base_url);
-    
-    // Warm-up test with more conservative settings
-    println!("🔥 Running warm-up test (1,000 requests)...");
-    client.run_load_test(1_000, 50, 10).await;
-    
-    // Reset counters
-    client.total_requests.store(0, Ordering::Relaxed);
-    client.successful_requests.store(0, Ordering::Relaxed);
-    client.failed_requests.store(0, Ordering::Relaxed);
-    
-    println!("\n{}", "=".repeat(60));
-    println!("🎯 MAIN CHALLENGE: 1,000,000 REQUESTS");
-    println!("{}", "=".repeat(60));
-    
-    // Main load test with more conservative settings
-    client.run_load_test(10, 10, 20).await;
+
+    match parse_mode() {
+        Mode::Closed => {
+            // Warm-up test with more conservative settings
+            println!("🔥 Running warm-up test (1,000 requests)...");
+            client.run_load_test(1_000, 50, 10).await;
+
+            // Reset counters
+            client.total_requests.store(0, Ordering::Relaxed);
+            client.successful_requests.store(0, Ordering::Relaxed);
+            client.failed_requests.store(0, Ordering::Relaxed);
+
+            println!("\n{}", "=".repeat(60));
+            println!("🎯 MAIN CHALLENGE: 1,000,000 REQUESTS");
+            println!("{}", "=".repeat(60));
+
+            // Main load test with more conservative settings
+            client.run_load_test(10, 10, 20).await;
+        }
+        Mode::Rate => {
+            client
+                .run_rate_controlled(50.0, 50.0, 500.0, Duration::from_secs(5), 10)
+                .await;
+        }
+        Mode::Batch => {
+            println!("🔥 Running warm-up test (1,000 requests, batched)...");
+            client.run_batch_load_test(1_000, 50, 10).await;
+
+            client.total_requests.store(0, Ordering::Relaxed);
+            client.successful_requests.store(0, Ordering::Relaxed);
+            client.failed_requests.store(0, Ordering::Relaxed);
+
+            println!("\n{}", "=".repeat(60));
+            println!("🎯 MAIN CHALLENGE: 1,000,000 REQUESTS (batched)");
+            println!("{}", "=".repeat(60));
+
+            client.run_batch_load_test(1_000_000, 1_000, 20).await;
+        }
+        Mode::Ws => {
+            println!("🚀 Streaming 1,000,000 requests over a single /ws connection...");
+            if let Err(e) = client.run_ws_load_test(1_000_000, 0).await {
+                eprintln!("❌ WebSocket load test failed: {}", e);
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,109 @@
+//! Fixed-memory cardinality estimation for `unique_users`, used in place of a
+//! `DashSet<String>` when the `hll` feature is enabled so memory and insert
+//! cost stay constant regardless of how many distinct users are seen.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Number of register index bits. `m = 2^PRECISION` registers of one byte
+/// each, so `PRECISION = 14` costs 16KB regardless of true cardinality.
+const PRECISION: u32 = 14;
+const NUM_REGISTERS: usize = 1 << PRECISION;
+
+/// Lock-free HyperLogLog sketch backed by `m` `AtomicU8` registers.
+///
+/// Each `add` hashes the input to 64 bits, uses the top `PRECISION` bits to
+/// pick a register, and the leading-zero count of the rest plus one as the
+/// rank (at most `(64 - PRECISION) + 1`, reached when the rest is all zero).
+/// Registers are updated with a fetch-max CAS loop so concurrent writers
+/// never need a lock.
+pub struct HyperLogLog {
+    registers: Box<[AtomicU8]>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        let registers = (0..NUM_REGISTERS)
+            .map(|_| AtomicU8::new(0))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        Self { registers }
+    }
+
+    pub fn add<T: Hash>(&self, value: &T) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let index = (hash >> (64 - PRECISION)) as usize;
+        // The remaining `64 - PRECISION` bits, left-aligned in a 64-bit word
+        // with zero padding below them. `leading_zeros` only sees that
+        // padding when `rest` is entirely zero, so that case is clamped to
+        // the documented sentinel rank of `(64 - PRECISION) + 1` instead of
+        // letting the padding bits inflate it further.
+        let rest = hash << PRECISION;
+        let rank = if rest == 0 {
+            (64 - PRECISION + 1) as u8
+        } else {
+            (rest.leading_zeros() + 1) as u8
+        };
+
+        let register = &self.registers[index];
+        let mut current = register.load(Ordering::Relaxed);
+        while rank > current {
+            match register.compare_exchange_weak(
+                current,
+                rank,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(observed) => current = observed,
+            }
+        }
+    }
+
+    /// Export the raw register bytes for persistence.
+    pub fn registers(&self) -> Vec<u8> {
+        self.registers
+            .iter()
+            .map(|r| r.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Rebuild a sketch from previously-exported register bytes.
+    pub fn from_registers(registers: &[u8]) -> Self {
+        let registers = registers
+            .iter()
+            .map(|&value| AtomicU8::new(value))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        Self { registers }
+    }
+
+    /// Estimate the number of distinct values added so far.
+    pub fn estimate(&self) -> f64 {
+        let m = NUM_REGISTERS as f64;
+        let alpha_m = 0.7213 / (1.0 + 1.079 / m);
+
+        let mut sum_inv = 0.0;
+        let mut zero_registers = 0usize;
+        for register in self.registers.iter() {
+            let value = register.load(Ordering::Relaxed);
+            if value == 0 {
+                zero_registers += 1;
+            }
+            sum_inv += 2f64.powi(-(value as i32));
+        }
+
+        let raw_estimate = alpha_m * m * m / sum_inv;
+
+        if raw_estimate <= 2.5 * m && zero_registers > 0 {
+            m * (m / zero_registers as f64).ln()
+        } else {
+            raw_estimate
+        }
+    }
+}
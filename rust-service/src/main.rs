@@ -1,9 +1,26 @@
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
+#[cfg(not(feature = "hll"))]
 use dashmap::DashSet;
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use warp::Filter;
 
+#[cfg(feature = "hll")]
+mod hyperloglog;
+#[cfg(feature = "hll")]
+use hyperloglog::HyperLogLog;
+
+mod stats;
+use stats::ValueStats;
+
+mod metrics;
+use metrics::LatencyHistogram;
+
+mod snapshot;
+use snapshot::{SnapshotData, UsersState};
+
 #[derive(Debug, Deserialize)]
 struct Event {
     #[serde(rename = "userId")]
@@ -11,6 +28,12 @@ struct Event {
     value: f64,
 }
 
+#[derive(Debug, Serialize)]
+struct BatchResponse {
+    accepted: usize,
+    rejected: usize,
+}
+
 #[derive(Debug, Serialize)]
 struct Stats {
     #[serde(rename = "totalRequests")]
@@ -19,48 +42,134 @@ struct Stats {
     unique_users: usize,
     sum: f64,
     avg: f64,
+    variance: f64,
+    stddev: f64,
+    min: f64,
+    max: f64,
 }
 
 #[derive(Clone)]
 struct EventStorage {
     total_requests: Arc<AtomicU64>,
-    sum: Arc<AtomicU64>, // Store as integer (multiply by 1000000 for precision)
+    value_stats: Arc<ValueStats>,
+    #[cfg(not(feature = "hll"))]
     users: Arc<DashSet<String>>,
+    // Approximate unique-user counting: constant ~16KB regardless of true
+    // cardinality, trading the exact count for ~1-2% error at scale.
+    #[cfg(feature = "hll")]
+    users: Arc<HyperLogLog>,
 }
 
 impl EventStorage {
     fn new() -> Self {
         Self {
             total_requests: Arc::new(AtomicU64::new(0)),
-            sum: Arc::new(AtomicU64::new(0)),
+            value_stats: Arc::new(ValueStats::new()),
+            #[cfg(not(feature = "hll"))]
             users: Arc::new(DashSet::new()),
+            #[cfg(feature = "hll")]
+            users: Arc::new(HyperLogLog::new()),
         }
     }
 
     fn add_event(&self, event: Event) {
         // Increment total requests atomically
         self.total_requests.fetch_add(1, Ordering::Relaxed);
-        
-        // Add to sum (multiply by 1000000 for precision)
-        let value_scaled = (event.value * 1_000_000.0) as u64;
-        self.sum.fetch_add(value_scaled, Ordering::Relaxed);
-        
-        // Add user to set (DashSet handles concurrency)
+
+        // Fold the value into Welford's online mean/variance, sharded by
+        // user_id so concurrent writers don't serialize on one lock.
+        self.value_stats.record(&event.user_id, event.value);
+
+        // Add user to set (DashSet handles concurrency) or, with the `hll`
+        // feature, fold it into the HyperLogLog sketch instead.
+        #[cfg(not(feature = "hll"))]
         self.users.insert(event.user_id);
+        #[cfg(feature = "hll")]
+        self.users.add(&event.user_id);
     }
 
     fn get_stats(&self) -> Stats {
         let total = self.total_requests.load(Ordering::Relaxed);
-        let sum_scaled = self.sum.load(Ordering::Relaxed);
-        let sum_actual = sum_scaled as f64 / 1_000_000.0;
+        #[cfg(not(feature = "hll"))]
         let unique_users = self.users.len();
-        let avg = if total > 0 { sum_actual / total as f64 } else { 0.0 };
+        #[cfg(feature = "hll")]
+        let unique_users = self.users.estimate().round() as usize;
+        let snapshot = self.value_stats.snapshot();
 
         Stats {
             total_requests: total,
             unique_users,
-            sum: sum_actual,
-            avg,
+            sum: snapshot.sum,
+            avg: snapshot.avg,
+            variance: snapshot.variance,
+            stddev: snapshot.stddev,
+            min: snapshot.min,
+            max: snapshot.max,
+        }
+    }
+
+    /// Builds a persistable copy of the current state.
+    ///
+    /// With the exact (non-`hll`) backing this clones every user ID out of
+    /// the `DashSet` and serializes all of them on every call, which is an
+    /// O(unique users) allocation + JSON encode — at 1M+ distinct users that
+    /// is a multi-MB snapshot taken every `SNAPSHOT_INTERVAL_SECS`. Deployments
+    /// at that scale should build with the `hll` feature, whose snapshot is
+    /// the fixed-size register array instead, or raise
+    /// `SNAPSHOT_INTERVAL_SECS` to amortize the cost.
+    fn to_snapshot_data(&self) -> SnapshotData {
+        #[cfg(not(feature = "hll"))]
+        let users = UsersState::Exact(self.users.iter().map(|u| u.clone()).collect());
+        #[cfg(feature = "hll")]
+        let users = UsersState::Hll(self.users.registers());
+
+        SnapshotData {
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            value_stats: self.value_stats.state(),
+            users,
+        }
+    }
+
+    fn from_snapshot_data(data: SnapshotData) -> Self {
+        #[cfg(not(feature = "hll"))]
+        let users = Arc::new(match data.users {
+            UsersState::Exact(ids) => {
+                let set = DashSet::new();
+                for id in ids {
+                    set.insert(id);
+                }
+                set
+            }
+            // The exact backing has no way to recover the original IDs a
+            // sketch folded away, so this restores to an empty set instead
+            // of silently pretending the count is still accurate.
+            UsersState::Hll(_) => {
+                eprintln!(
+                    "⚠️  Snapshot has an HLL-backed user sketch but this build uses the exact \
+                     (non-hll) backing; unique-user count resets to 0 instead of being recovered."
+                );
+                DashSet::new()
+            }
+        });
+        #[cfg(feature = "hll")]
+        let users = Arc::new(match data.users {
+            UsersState::Hll(registers) => HyperLogLog::from_registers(&registers),
+            // The exact backing's IDs are still available, so re-seed the
+            // sketch by hashing each one in rather than discarding them and
+            // silently resetting the unique-user count to 0.
+            UsersState::Exact(ids) => {
+                let sketch = HyperLogLog::new();
+                for id in &ids {
+                    sketch.add(id);
+                }
+                sketch
+            }
+        });
+
+        Self {
+            total_requests: Arc::new(AtomicU64::new(data.total_requests)),
+            value_stats: Arc::new(ValueStats::restore(data.value_stats)),
+            users,
         }
     }
 }
@@ -68,39 +177,238 @@ impl EventStorage {
 async fn handle_event(
     event: Event,
     storage: Arc<EventStorage>,
+    latency: Arc<LatencyHistogram>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let start = Instant::now();
     storage.add_event(event);
+    latency.observe(start.elapsed());
     Ok(warp::reply::with_status("OK", warp::http::StatusCode::OK))
 }
 
+async fn handle_events_batch(
+    items: Vec<serde_json::Value>,
+    storage: Arc<EventStorage>,
+    latency: Arc<LatencyHistogram>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut accepted = 0usize;
+    let mut rejected = 0usize;
+    for item in items {
+        // Observe each event's own `add_event` cost rather than the whole
+        // batch as one sample, so `events_handle_latency_seconds` stays
+        // comparable to the per-event `/event` route and its `_count`
+        // tracks the number of events processed, not the number of requests.
+        match serde_json::from_value::<Event>(item) {
+            Ok(event) => {
+                let start = Instant::now();
+                storage.add_event(event);
+                latency.observe(start.elapsed());
+                accepted += 1;
+            }
+            Err(_) => rejected += 1,
+        }
+    }
+
+    Ok(warp::reply::json(&BatchResponse { accepted, rejected }))
+}
+
 async fn handle_stats(storage: Arc<EventStorage>) -> Result<impl warp::Reply, warp::Rejection> {
     let stats = storage.get_stats();
     Ok(warp::reply::json(&stats))
 }
 
+async fn handle_metrics(
+    storage: Arc<EventStorage>,
+    latency: Arc<LatencyHistogram>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let stats = storage.get_stats();
+    let body = metrics::render_prometheus(
+        stats.total_requests,
+        stats.unique_users,
+        stats.sum,
+        stats.avg,
+        &latency,
+    );
+    Ok(warp::reply::with_header(
+        body,
+        "Content-Type",
+        "text/plain; version=0.0.4",
+    ))
+}
+
+/// Number of aggregator tasks draining the WebSocket event queue. All sockets
+/// share the one queue, so socket readers never apply updates themselves.
+const NUM_WS_AGGREGATORS: usize = 4;
+
+async fn handle_ws_upgrade(
+    ws: warp::ws::Ws,
+    tx: flume::Sender<Event>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(ws.on_upgrade(move |socket| handle_ws_connection(socket, tx)))
+}
+
+/// Reads frame-delimited `Event` JSON off the socket and hands each one to
+/// an aggregator task over `tx`, keeping the read path non-blocking instead
+/// of paying a full request's cost per event.
+async fn handle_ws_connection(socket: warp::ws::WebSocket, tx: flume::Sender<Event>) {
+    let (_sink, mut stream) = socket.split();
+
+    while let Some(Ok(message)) = stream.next().await {
+        if !message.is_text() && !message.is_binary() {
+            continue;
+        }
+        if let Ok(event) = serde_json::from_slice::<Event>(message.as_bytes()) {
+            if tx.send_async(event).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Default path for the periodic state snapshot, overridable via the
+/// `SNAPSHOT_PATH` env var.
+const DEFAULT_SNAPSHOT_PATH: &str = "snapshot.json";
+/// Default snapshot interval in seconds, overridable via
+/// `SNAPSHOT_INTERVAL_SECS`.
+const DEFAULT_SNAPSHOT_INTERVAL_SECS: u64 = 30;
+
+fn snapshot_path() -> std::path::PathBuf {
+    std::env::var("SNAPSHOT_PATH")
+        .unwrap_or_else(|_| DEFAULT_SNAPSHOT_PATH.to_string())
+        .into()
+}
+
+fn snapshot_interval() -> std::time::Duration {
+    let secs = std::env::var("SNAPSHOT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SNAPSHOT_INTERVAL_SECS);
+    std::time::Duration::from_secs(secs)
+}
+
+fn save_snapshot(path: &std::path::Path, storage: &EventStorage) {
+    if let Err(e) = snapshot::write_atomic(path, &storage.to_snapshot_data()) {
+        eprintln!("Failed to write snapshot to {}: {}", path.display(), e);
+    }
+}
+
+/// Resolves on Ctrl-C or, on Unix, SIGTERM, so a container orchestrator's
+/// `docker stop`/k8s termination signal triggers a graceful shutdown too.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    let storage = Arc::new(EventStorage::new());
+    let snapshot_path = snapshot_path();
+    let snapshot_interval = snapshot_interval();
+
+    let storage = match snapshot::read(&snapshot_path) {
+        Ok(Some(data)) => {
+            println!("Restored state from snapshot at {}", snapshot_path.display());
+            Arc::new(EventStorage::from_snapshot_data(data))
+        }
+        Ok(None) => Arc::new(EventStorage::new()),
+        Err(e) => {
+            eprintln!(
+                "Failed to read snapshot at {}: {} (starting fresh)",
+                snapshot_path.display(),
+                e
+            );
+            Arc::new(EventStorage::new())
+        }
+    };
+    let latency = Arc::new(LatencyHistogram::new());
+
+    let storage_for_snapshots = storage.clone();
+    let snapshot_path_for_periodic = snapshot_path.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(snapshot_interval);
+        loop {
+            ticker.tick().await;
+            save_snapshot(&snapshot_path_for_periodic, &storage_for_snapshots);
+        }
+    });
+
+    let (ws_event_tx, ws_event_rx) = flume::unbounded::<Event>();
+    for _ in 0..NUM_WS_AGGREGATORS {
+        let storage = storage.clone();
+        let rx = ws_event_rx.clone();
+        tokio::spawn(async move {
+            while let Ok(event) = rx.recv_async().await {
+                storage.add_event(event);
+            }
+        });
+    }
 
     let storage_for_events = storage.clone();
+    let storage_for_batch = storage.clone();
     let storage_for_stats = storage.clone();
+    let storage_for_metrics = storage.clone();
+    let latency_for_events = latency.clone();
+    let latency_for_batch = latency.clone();
+    let latency_for_metrics = latency.clone();
 
     let event_route = warp::path("event")
         .and(warp::post())
         .and(warp::body::json())
         .and(warp::any().map(move || storage_for_events.clone()))
+        .and(warp::any().map(move || latency_for_events.clone()))
         .and_then(handle_event);
 
+    let events_batch_route = warp::path("events")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(warp::any().map(move || storage_for_batch.clone()))
+        .and(warp::any().map(move || latency_for_batch.clone()))
+        .and_then(handle_events_batch);
+
     let stats_route = warp::path("stats")
         .and(warp::get())
         .and(warp::any().map(move || storage_for_stats.clone()))
         .and_then(handle_stats);
 
-    let routes = event_route.or(stats_route);
+    let metrics_route = warp::path("metrics")
+        .and(warp::get())
+        .and(warp::any().map(move || storage_for_metrics.clone()))
+        .and(warp::any().map(move || latency_for_metrics.clone()))
+        .and_then(handle_metrics);
+
+    let ws_route = warp::path("ws")
+        .and(warp::ws())
+        .and(warp::any().map(move || ws_event_tx.clone()))
+        .and_then(handle_ws_upgrade);
+
+    let routes = event_route
+        .or(events_batch_route)
+        .or(stats_route)
+        .or(metrics_route)
+        .or(ws_route);
 
     println!("Starting server on http://localhost:8080");
-    
-    warp::serve(routes)
-        .run(([0, 0, 0, 0], 8080))
-        .await;
+
+    let (_addr, server) = warp::serve(routes)
+        .bind_with_graceful_shutdown(([0, 0, 0, 0], 8080), shutdown_signal());
+    server.await;
+
+    println!("Shutting down, writing final snapshot to {}", snapshot_path.display());
+    save_snapshot(&snapshot_path, &storage);
 }
\ No newline at end of file
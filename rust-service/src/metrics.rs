@@ -0,0 +1,96 @@
+//! Prometheus text-exposition rendering for the `/metrics` route: gauges
+//! sourced from `EventStorage::get_stats`, plus a lock-free latency
+//! histogram of per-event `add_event` time on the `/event` and `/events`
+//! routes.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds (seconds) for the latency histogram buckets, spanning
+/// 0.1ms to 1s. The final `+Inf` bucket is implicit in rendering.
+const BUCKET_BOUNDS_SECS: [f64; 9] = [
+    0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// Lock-free histogram of per-event handling latency. Each observation bumps
+/// exactly one bucket counter (the first bound it fits under) via
+/// `fetch_add`; cumulative `_bucket` counts are computed at render time.
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; BUCKET_BOUNDS_SECS.len()],
+    overflow: AtomicU64,
+    sum_nanos: AtomicU64,
+    count: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: Default::default(),
+            overflow: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        match BUCKET_BOUNDS_SECS.iter().position(|&bound| secs <= bound) {
+            Some(index) => {
+                self.buckets[index].fetch_add(1, Ordering::Relaxed);
+            }
+            None => {
+                self.overflow.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render `name_bucket`/`_sum`/`_count` lines in Prometheus text format.
+    fn render(&self, name: &str, out: &mut String) {
+        out.push_str(&format!("# TYPE {name} histogram\n"));
+
+        let mut cumulative = 0u64;
+        for (bound, bucket) in BUCKET_BOUNDS_SECS.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            out.push_str(&format!(
+                "{name}_bucket{{le=\"{bound}\"}} {cumulative}\n"
+            ));
+        }
+        cumulative += self.overflow.load(Ordering::Relaxed);
+        out.push_str(&format!("{name}_bucket{{le=\"+Inf\"}} {cumulative}\n"));
+
+        let sum_secs = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1_000_000_000.0;
+        out.push_str(&format!("{name}_sum {sum_secs}\n"));
+        out.push_str(&format!("{name}_count {}\n", self.count.load(Ordering::Relaxed)));
+    }
+}
+
+/// Render the full `/metrics` response body: ingestion gauges plus the
+/// per-event handling latency histogram.
+pub fn render_prometheus(
+    total_requests: u64,
+    unique_users: usize,
+    sum: f64,
+    avg: f64,
+    latency: &LatencyHistogram,
+) -> String {
+    let mut out = String::new();
+
+    out.push_str("# TYPE events_total_requests counter\n");
+    out.push_str(&format!("events_total_requests {total_requests}\n"));
+
+    out.push_str("# TYPE events_unique_users gauge\n");
+    out.push_str(&format!("events_unique_users {unique_users}\n"));
+
+    out.push_str("# TYPE events_sum gauge\n");
+    out.push_str(&format!("events_sum {sum}\n"));
+
+    out.push_str("# TYPE events_avg gauge\n");
+    out.push_str(&format!("events_avg {avg}\n"));
+
+    latency.render("events_handle_latency_seconds", &mut out);
+
+    out
+}
@@ -0,0 +1,54 @@
+//! On-disk persistence for `EventStorage`: a compact JSON snapshot written
+//! atomically (temp-file-and-rename) so a crash mid-write can't corrupt the
+//! file the server reloads at startup.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::stats::ValueStatsState;
+
+/// Unique-user backing persisted alongside the rest of the snapshot. Mirrors
+/// whichever of `DashSet<String>` / `HyperLogLog` `EventStorage` is built
+/// with, so a snapshot taken under one feature set can still be read back
+/// under the other — but only `Exact -> Hll` carries the unique users over
+/// (by re-hashing the stored IDs into a fresh sketch, whose count is then an
+/// estimate like any other HyperLogLog count); `Hll ->
+/// Exact` has no IDs to recover and resets to an empty set, logging a
+/// warning when it does.
+#[derive(Serialize, Deserialize)]
+pub enum UsersState {
+    Exact(Vec<String>),
+    Hll(Vec<u8>),
+}
+
+/// Full persisted state of `EventStorage`.
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotData {
+    pub total_requests: u64,
+    pub value_stats: ValueStatsState,
+    pub users: UsersState,
+}
+
+/// Serialize `data` and write it to `path` atomically: write to a sibling
+/// temp file, then rename it over the destination.
+pub fn write_atomic(path: &Path, data: &SnapshotData) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let body = serde_json::to_vec(data)?;
+    std::fs::write(&tmp_path, body)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Read and deserialize a snapshot previously written by [`write_atomic`].
+/// Returns `Ok(None)` if no snapshot exists yet (e.g. first run).
+pub fn read(path: &Path) -> std::io::Result<Option<SnapshotData>> {
+    match std::fs::read(path) {
+        Ok(body) => {
+            let data = serde_json::from_slice(&body)?;
+            Ok(Some(data))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,215 @@
+//! Streaming value statistics (mean, variance, min/max) computed with
+//! Welford's online algorithm instead of a scaled-integer running sum, so
+//! precision doesn't degrade and `AtomicU64` can't overflow at scale.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Number of mutex-guarded accumulator shards. Events are routed to a shard
+/// by hashing `user_id`, keeping concurrent writers from serializing on a
+/// single lock while still allowing an exact merge at read time.
+const NUM_SHARDS: usize = 16;
+
+#[derive(Clone, Copy, Default)]
+struct ShardAccumulator {
+    n: u64,
+    mean: f64,
+    m2: f64,
+    sum: f64,
+}
+
+impl ShardAccumulator {
+    fn add(&mut self, x: f64) {
+        self.n += 1;
+        self.sum += x;
+        let delta = x - self.mean;
+        self.mean += delta / self.n as f64;
+        let delta2 = x - self.mean;
+        self.m2 += delta * delta2;
+    }
+
+    /// Chan et al.'s parallel combination rule for merging two shards'
+    /// running statistics into one.
+    fn merge(self, other: ShardAccumulator) -> ShardAccumulator {
+        if self.n == 0 {
+            return other;
+        }
+        if other.n == 0 {
+            return self;
+        }
+
+        let n = self.n + other.n;
+        let delta = other.mean - self.mean;
+        let mean = (self.n as f64 * self.mean + other.n as f64 * other.mean) / n as f64;
+        let m2 = self.m2 + other.m2 + delta * delta * (self.n as f64 * other.n as f64) / n as f64;
+
+        ShardAccumulator {
+            n,
+            mean,
+            m2,
+            sum: self.sum + other.sum,
+        }
+    }
+}
+
+/// Aggregate snapshot returned by [`ValueStats::snapshot`].
+pub struct ValueStatsSnapshot {
+    pub sum: f64,
+    pub avg: f64,
+    pub variance: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Raw accumulator state, sufficient to exactly restore a [`ValueStats`]
+/// (unlike [`ValueStatsSnapshot`], which only carries derived values).
+///
+/// `min`/`max` are `None` when `n == 0` — the unseeded sentinels are
+/// `+-INFINITY`, which `serde_json` can't round-trip as plain `f64`s (they
+/// serialize to `null` and then fail to deserialize back).
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct ValueStatsState {
+    pub n: u64,
+    pub mean: f64,
+    pub m2: f64,
+    pub sum: f64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// Concurrent running statistics over a stream of `f64` values.
+pub struct ValueStats {
+    shards: [Mutex<ShardAccumulator>; NUM_SHARDS],
+    min_bits: AtomicU64,
+    max_bits: AtomicU64,
+}
+
+impl ValueStats {
+    pub fn new() -> Self {
+        Self {
+            shards: [(); NUM_SHARDS].map(|_| Mutex::new(ShardAccumulator::default())),
+            min_bits: AtomicU64::new(f64::INFINITY.to_bits()),
+            max_bits: AtomicU64::new(f64::NEG_INFINITY.to_bits()),
+        }
+    }
+
+    pub fn record(&self, user_id: &str, value: f64) {
+        let shard = shard_for(user_id);
+        self.shards[shard].lock().unwrap().add(value);
+        fetch_min(&self.min_bits, value);
+        fetch_max(&self.max_bits, value);
+    }
+
+    pub fn snapshot(&self) -> ValueStatsSnapshot {
+        let merged = self
+            .shards
+            .iter()
+            .map(|shard| *shard.lock().unwrap())
+            .fold(ShardAccumulator::default(), ShardAccumulator::merge);
+
+        let variance = if merged.n > 0 { merged.m2 / merged.n as f64 } else { 0.0 };
+        let avg = if merged.n > 0 { merged.sum / merged.n as f64 } else { 0.0 };
+        let (min, max) = if merged.n > 0 {
+            (
+                f64::from_bits(self.min_bits.load(Ordering::Relaxed)),
+                f64::from_bits(self.max_bits.load(Ordering::Relaxed)),
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        ValueStatsSnapshot {
+            sum: merged.sum,
+            avg,
+            variance,
+            stddev: variance.sqrt(),
+            min,
+            max,
+        }
+    }
+
+    /// Export the merged accumulator state for persistence.
+    pub fn state(&self) -> ValueStatsState {
+        let merged = self
+            .shards
+            .iter()
+            .map(|shard| *shard.lock().unwrap())
+            .fold(ShardAccumulator::default(), ShardAccumulator::merge);
+
+        let (min, max) = if merged.n > 0 {
+            (
+                Some(f64::from_bits(self.min_bits.load(Ordering::Relaxed))),
+                Some(f64::from_bits(self.max_bits.load(Ordering::Relaxed))),
+            )
+        } else {
+            (None, None)
+        };
+
+        ValueStatsState {
+            n: merged.n,
+            mean: merged.mean,
+            m2: merged.m2,
+            sum: merged.sum,
+            min,
+            max,
+        }
+    }
+
+    /// Rebuild a `ValueStats` from persisted state, loading it all into a
+    /// single shard; later events are sharded as usual and merge correctly
+    /// with it via [`ShardAccumulator::merge`].
+    pub fn restore(state: ValueStatsState) -> Self {
+        let restored = Self::new();
+        *restored.shards[0].lock().unwrap() = ShardAccumulator {
+            n: state.n,
+            mean: state.mean,
+            m2: state.m2,
+            sum: state.sum,
+        };
+        if let (Some(min), Some(max)) = (state.min, state.max) {
+            restored.min_bits.store(min.to_bits(), Ordering::Relaxed);
+            restored.max_bits.store(max.to_bits(), Ordering::Relaxed);
+        }
+        restored
+    }
+}
+
+fn shard_for(user_id: &str) -> usize {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    user_id.hash(&mut hasher);
+    (hasher.finish() as usize) % NUM_SHARDS
+}
+
+fn fetch_min(bits: &AtomicU64, value: f64) {
+    let mut current = bits.load(Ordering::Relaxed);
+    while value < f64::from_bits(current) {
+        match bits.compare_exchange_weak(
+            current,
+            value.to_bits(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => break,
+            Err(observed) => current = observed,
+        }
+    }
+}
+
+fn fetch_max(bits: &AtomicU64, value: f64) {
+    let mut current = bits.load(Ordering::Relaxed);
+    while value > f64::from_bits(current) {
+        match bits.compare_exchange_weak(
+            current,
+            value.to_bits(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => break,
+            Err(observed) => current = observed,
+        }
+    }
+}